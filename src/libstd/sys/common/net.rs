@@ -10,17 +10,36 @@
 
 use prelude::v1::*;
 
+use cmp;
 use ffi::{CStr, CString};
 use fmt;
-use io::{self, Error, ErrorKind};
+use io::{self, Error, ErrorKind, IoSlice, IoSliceMut};
 use libc::{self, c_int, c_char, c_void, socklen_t};
 use mem;
-use net::{SocketAddr, Shutdown, IpAddr};
+use net::{SocketAddr, Shutdown, IpAddr, Ipv4Addr, Ipv6Addr};
 use str::from_utf8;
 use sys::c;
 use sys::net::{cvt, cvt_r, cvt_gai, Socket, init, wrlen_t};
 use sys_common::{AsInner, FromInner, IntoInner};
-use time::Duration;
+use time::{Duration, Instant};
+
+////////////////////////////////////////////////////////////////////////////////
+// scatter/gather I/O
+////////////////////////////////////////////////////////////////////////////////
+
+// The maximum number of `iovec`s that a single `readv`/`writev` call (or, on
+// Windows, a single `WSARecv`/`WSASend` call) will be given. POSIX guarantees
+// at least this many via `IOV_MAX`; we just cap at it ourselves instead of
+// querying it so that callers can pass arbitrarily large buffer lists.
+#[cfg(unix)]
+fn max_iov() -> usize {
+    libc::IOV_MAX as usize
+}
+
+#[cfg(windows)]
+fn max_iov() -> usize {
+    1024
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 // sockaddr and misc bindings
@@ -81,6 +100,22 @@ fn sockaddr_to_addr(storage: &libc::sockaddr_storage,
     }
 }
 
+fn ip4_to_inaddr(ip: &Ipv4Addr) -> libc::in_addr {
+    let octets = ip.octets();
+    libc::in_addr {
+        s_addr: ((octets[0] as u32) << 0) |
+                ((octets[1] as u32) << 8) |
+                ((octets[2] as u32) << 16) |
+                ((octets[3] as u32) << 24),
+    }
+}
+
+fn ip6_to_in6addr(ip: &Ipv6Addr) -> libc::in6_addr {
+    let mut addr: libc::in6_addr = unsafe { mem::zeroed() };
+    addr.s6_addr = ip.octets();
+    addr
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // get_host_addresses
 ////////////////////////////////////////////////////////////////////////////////
@@ -166,6 +201,145 @@ pub fn lookup_addr(addr: &IpAddr) -> io::Result<String> {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// non-blocking mode
+////////////////////////////////////////////////////////////////////////////////
+
+// Once a socket is in non-blocking mode, a `WouldBlock` error from
+// `read`/`write`/`accept` becomes the caller's signal to wait for readiness
+// elsewhere (e.g. in their own `poll`/`epoll`/`kqueue` loop) and try again.
+#[cfg(unix)]
+fn set_nonblocking(sock: &Socket, nonblocking: bool) -> io::Result<()> {
+    unsafe {
+        let fd = *sock.as_inner();
+        let previous = try!(cvt(libc::fcntl(fd, libc::F_GETFL, 0)));
+        let new = if nonblocking {
+            previous | libc::O_NONBLOCK
+        } else {
+            previous & !libc::O_NONBLOCK
+        };
+        if new != previous {
+            try!(cvt(libc::fcntl(fd, libc::F_SETFL, new)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_nonblocking(sock: &Socket, nonblocking: bool) -> io::Result<()> {
+    let mut nonblocking = nonblocking as libc::c_ulong;
+    try!(cvt(unsafe {
+        c::ioctlsocket(*sock.as_inner(), c::FIONBIO, &mut nonblocking)
+    }));
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// connecting with a timeout
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(unix)]
+fn would_block(err: &Error) -> bool {
+    err.raw_os_error() == Some(libc::EINPROGRESS)
+}
+
+#[cfg(windows)]
+fn would_block(err: &Error) -> bool {
+    err.raw_os_error() == Some(c::WSAEWOULDBLOCK)
+}
+
+// Round any nonzero remainder up to at least 1ms so a sub-millisecond amount
+// of time left (e.g. right after an `EINTR` retry) still gets a real `poll`
+// wait instead of an immediate, spuriously-early `TimedOut`.
+fn poll_timeout_ms(remaining: Duration) -> c_int {
+    let ms = cmp::min(remaining.as_secs().saturating_mul(1_000)
+                           .saturating_add((remaining.subsec_nanos() / 1_000_000) as u64),
+                       c_int::max_value() as u64);
+    if ms == 0 && remaining > Duration::new(0, 0) { 1 } else { ms as c_int }
+}
+
+// Waits (via `poll`) for `sock` to become writable, or for `timeout` to
+// elapse, then checks `SO_ERROR` to see whether the pending `connect`
+// actually succeeded. A `poll` interrupted by a signal retries with
+// whatever time is left.
+#[cfg(unix)]
+fn poll_connect(sock: &Socket, timeout: Duration) -> io::Result<()> {
+    let fd = *sock.as_inner();
+    let start = Instant::now();
+    let mut remaining = timeout;
+
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd: fd,
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+
+        let ms = poll_timeout_ms(remaining);
+        let ret = unsafe { libc::poll(&mut pollfd, 1, ms) };
+        if ret == -1 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(Error::new(ErrorKind::TimedOut, "connect timed out"));
+                }
+                remaining = timeout - elapsed;
+                continue;
+            }
+            return Err(err);
+        } else if ret == 0 {
+            return Err(Error::new(ErrorKind::TimedOut, "connect timed out"));
+        }
+
+        let raw: c_int = try!(getsockopt(sock, libc::SOL_SOCKET, libc::SO_ERROR));
+        return if raw == 0 {
+            Ok(())
+        } else {
+            Err(Error::from_raw_os_error(raw))
+        };
+    }
+}
+
+#[cfg(windows)]
+fn poll_connect(sock: &Socket, timeout: Duration) -> io::Result<()> {
+    let fd = *sock.as_inner();
+    let start = Instant::now();
+    let mut remaining = timeout;
+
+    loop {
+        let mut pollfd = c::WSAPOLLFD {
+            fd: fd,
+            events: c::POLLOUT,
+            revents: 0,
+        };
+
+        let ms = poll_timeout_ms(remaining);
+        let ret = unsafe { c::WSAPoll(&mut pollfd, 1, ms) };
+        if ret == -1 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(Error::new(ErrorKind::TimedOut, "connect timed out"));
+                }
+                remaining = timeout - elapsed;
+                continue;
+            }
+            return Err(err);
+        } else if ret == 0 {
+            return Err(Error::new(ErrorKind::TimedOut, "connect timed out"));
+        }
+
+        let raw: c_int = try!(getsockopt(sock, libc::SOL_SOCKET, libc::SO_ERROR));
+        return if raw == 0 {
+            Ok(())
+        } else {
+            Err(Error::from_raw_os_error(raw))
+        };
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // TCP streams
 ////////////////////////////////////////////////////////////////////////////////
@@ -185,6 +359,29 @@ impl TcpStream {
         Ok(TcpStream { inner: sock })
     }
 
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        init();
+
+        if timeout.as_secs() == 0 && timeout.subsec_nanos() == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "cannot set a 0 duration timeout"));
+        }
+
+        let sock = try!(Socket::new(addr, libc::SOCK_STREAM));
+        try!(set_nonblocking(&sock, true));
+
+        let (addrp, len) = addr.into_inner();
+        let connected = cvt(unsafe { libc::connect(*sock.as_inner(), addrp, len) });
+        match connected {
+            Ok(_) => {}
+            Err(ref e) if would_block(e) => try!(poll_connect(&sock, timeout)),
+            Err(e) => return Err(e),
+        }
+
+        try!(set_nonblocking(&sock, false));
+        Ok(TcpStream { inner: sock })
+    }
+
     pub fn socket(&self) -> &Socket { &self.inner }
 
     pub fn into_socket(self) -> Socket { self.inner }
@@ -219,6 +416,67 @@ impl TcpStream {
         Ok(ret as usize)
     }
 
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let ret = try!(cvt(unsafe {
+            libc::recv(*self.inner.as_inner(),
+                       buf.as_mut_ptr() as *mut c_void,
+                       buf.len() as wrlen_t,
+                       libc::MSG_PEEK)
+        }));
+        Ok(ret as usize)
+    }
+
+    #[cfg(unix)]
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let ret = try!(cvt(unsafe {
+            libc::readv(*self.inner.as_inner(),
+                        bufs.as_ptr() as *const libc::iovec,
+                        len as c_int)
+        }));
+        Ok(ret as usize)
+    }
+
+    #[cfg(windows)]
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let mut nread = 0;
+        let mut flags = 0;
+        try!(cvt(unsafe {
+            c::WSARecv(*self.inner.as_inner(),
+                       bufs.as_mut_ptr() as *mut c::WSABUF,
+                       len as libc::DWORD,
+                       &mut nread, &mut flags,
+                       0 as *mut _, None)
+        }));
+        Ok(nread as usize)
+    }
+
+    #[cfg(unix)]
+    pub fn write_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let ret = try!(cvt(unsafe {
+            libc::writev(*self.inner.as_inner(),
+                         bufs.as_ptr() as *const libc::iovec,
+                         len as c_int)
+        }));
+        Ok(ret as usize)
+    }
+
+    #[cfg(windows)]
+    pub fn write_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let mut nwritten = 0;
+        try!(cvt(unsafe {
+            c::WSASend(*self.inner.as_inner(),
+                       bufs.as_ptr() as *mut c::WSABUF,
+                       len as libc::DWORD,
+                       &mut nwritten, 0,
+                       0 as *mut _, None)
+        }));
+        Ok(nwritten as usize)
+    }
+
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         sockname(|buf, len| unsafe {
             libc::getpeername(*self.inner.as_inner(), buf, len)
@@ -246,6 +504,31 @@ impl TcpStream {
     pub fn duplicate(&self) -> io::Result<TcpStream> {
         self.inner.duplicate().map(|s| TcpStream { inner: s })
     }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_TCP, libc::TCP_NODELAY,
+                   nodelay as c_int)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_TCP,
+                                         libc::TCP_NODELAY));
+        Ok(raw != 0)
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IP,
+                                         libc::IP_TTL));
+        Ok(raw as u32)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        set_nonblocking(&self.inner, nonblocking)
+    }
 }
 
 impl FromInner<Socket> for TcpStream {
@@ -325,6 +608,20 @@ impl TcpListener {
     pub fn duplicate(&self) -> io::Result<TcpListener> {
         self.inner.duplicate().map(|s| TcpListener { inner: s })
     }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IP,
+                                         libc::IP_TTL));
+        Ok(raw as u32)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        set_nonblocking(&self.inner, nonblocking)
+    }
 }
 
 impl FromInner<Socket> for TcpListener {
@@ -388,6 +685,19 @@ impl UdpSocket {
         Ok((n as usize, try!(sockaddr_to_addr(&storage, addrlen as usize))))
     }
 
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addrlen = mem::size_of_val(&storage) as socklen_t;
+
+        let n = try!(cvt(unsafe {
+            libc::recvfrom(*self.inner.as_inner(),
+                           buf.as_mut_ptr() as *mut c_void,
+                           buf.len() as wrlen_t, libc::MSG_PEEK,
+                           &mut storage as *mut _ as *mut _, &mut addrlen)
+        }));
+        Ok((n as usize, try!(sockaddr_to_addr(&storage, addrlen as usize))))
+    }
+
     pub fn send_to(&self, buf: &[u8], dst: &SocketAddr) -> io::Result<usize> {
         let (dstp, dstlen) = dst.into_inner();
         let ret = try!(cvt(unsafe {
@@ -398,10 +708,87 @@ impl UdpSocket {
         Ok(ret as usize)
     }
 
+    pub fn connect(&self, addr: &SocketAddr) -> io::Result<()> {
+        let (addrp, len) = addr.into_inner();
+        try!(cvt(unsafe { libc::connect(*self.inner.as_inner(), addrp, len) }));
+        Ok(())
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let ret = try!(cvt(unsafe {
+            libc::send(*self.inner.as_inner(),
+                       buf.as_ptr() as *const c_void,
+                       buf.len() as wrlen_t,
+                       0)
+        }));
+        Ok(ret as usize)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let ret = try!(cvt(unsafe {
+            libc::recv(*self.inner.as_inner(),
+                       buf.as_mut_ptr() as *mut c_void,
+                       buf.len() as wrlen_t,
+                       0)
+        }));
+        Ok(ret as usize)
+    }
+
     pub fn duplicate(&self) -> io::Result<UdpSocket> {
         self.inner.duplicate().map(|s| UdpSocket { inner: s })
     }
 
+    #[cfg(unix)]
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let ret = try!(cvt(unsafe {
+            libc::readv(*self.inner.as_inner(),
+                        bufs.as_ptr() as *const libc::iovec,
+                        len as c_int)
+        }));
+        Ok(ret as usize)
+    }
+
+    #[cfg(windows)]
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let mut nread = 0;
+        let mut flags = 0;
+        try!(cvt(unsafe {
+            c::WSARecv(*self.inner.as_inner(),
+                       bufs.as_mut_ptr() as *mut c::WSABUF,
+                       len as libc::DWORD,
+                       &mut nread, &mut flags,
+                       0 as *mut _, None)
+        }));
+        Ok(nread as usize)
+    }
+
+    #[cfg(unix)]
+    pub fn write_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let ret = try!(cvt(unsafe {
+            libc::writev(*self.inner.as_inner(),
+                         bufs.as_ptr() as *const libc::iovec,
+                         len as c_int)
+        }));
+        Ok(ret as usize)
+    }
+
+    #[cfg(windows)]
+    pub fn write_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let len = cmp::min(bufs.len(), max_iov());
+        let mut nwritten = 0;
+        try!(cvt(unsafe {
+            c::WSASend(*self.inner.as_inner(),
+                       bufs.as_ptr() as *mut c::WSABUF,
+                       len as libc::DWORD,
+                       &mut nwritten, 0,
+                       0 as *mut _, None)
+        }));
+        Ok(nwritten as usize)
+    }
+
     pub fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
         self.inner.set_timeout(dur, libc::SO_RCVTIMEO)
     }
@@ -417,6 +804,111 @@ impl UdpSocket {
     pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
         self.inner.timeout(libc::SO_SNDTIMEO)
     }
+
+    pub fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        setsockopt(&self.inner, libc::SOL_SOCKET, libc::SO_BROADCAST,
+                   broadcast as c_int)
+    }
+
+    pub fn broadcast(&self) -> io::Result<bool> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::SOL_SOCKET,
+                                         libc::SO_BROADCAST));
+        Ok(raw != 0)
+    }
+
+    pub fn set_multicast_loop_v4(&self, on: bool) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_MULTICAST_LOOP,
+                   on as c_int)
+    }
+
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IP,
+                                         libc::IP_MULTICAST_LOOP));
+        Ok(raw != 0)
+    }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_MULTICAST_TTL,
+                   ttl as c_int)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IP,
+                                         libc::IP_MULTICAST_TTL));
+        Ok(raw as u32)
+    }
+
+    pub fn set_multicast_loop_v6(&self, on: bool) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_LOOP,
+                   on as c_int)
+    }
+
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IPV6,
+                                         libc::IPV6_MULTICAST_LOOP));
+        Ok(raw != 0)
+    }
+
+    pub fn set_multicast_ttl_v6(&self, ttl: u32) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_HOPS,
+                   ttl as c_int)
+    }
+
+    pub fn multicast_ttl_v6(&self) -> io::Result<u32> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IPV6,
+                                         libc::IPV6_MULTICAST_HOPS));
+        Ok(raw as u32)
+    }
+
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr)
+                             -> io::Result<()> {
+        let mreq = libc::ip_mreq {
+            imr_multiaddr: ip4_to_inaddr(multiaddr),
+            imr_interface: ip4_to_inaddr(interface),
+        };
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_ADD_MEMBERSHIP, mreq)
+    }
+
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32)
+                             -> io::Result<()> {
+        let mreq = libc::ipv6_mreq {
+            ipv6mr_multiaddr: ip6_to_in6addr(multiaddr),
+            ipv6mr_interface: interface,
+        };
+        setsockopt(&self.inner, libc::IPPROTO_IPV6, libc::IPV6_JOIN_GROUP, mreq)
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr)
+                              -> io::Result<()> {
+        let mreq = libc::ip_mreq {
+            imr_multiaddr: ip4_to_inaddr(multiaddr),
+            imr_interface: ip4_to_inaddr(interface),
+        };
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_DROP_MEMBERSHIP, mreq)
+    }
+
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32)
+                              -> io::Result<()> {
+        let mreq = libc::ipv6_mreq {
+            ipv6mr_multiaddr: ip6_to_in6addr(multiaddr),
+            ipv6mr_interface: interface,
+        };
+        setsockopt(&self.inner, libc::IPPROTO_IPV6, libc::IPV6_LEAVE_GROUP, mreq)
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        setsockopt(&self.inner, libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        let raw: c_int = try!(getsockopt(&self.inner, libc::IPPROTO_IP,
+                                         libc::IP_TTL));
+        Ok(raw as u32)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        set_nonblocking(&self.inner, nonblocking)
+    }
 }
 
 impl FromInner<Socket> for UdpSocket {